@@ -0,0 +1,138 @@
+use std::io;
+
+/// Sentinel marking the end of the free list.
+const NONE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Free,
+    InFlight,
+}
+
+struct Slot {
+    state: SlotState,
+    /// Index of the next free slot, or [`NONE`] if this is the tail of the free list.
+    next_free: u32,
+}
+
+/// Divides a ZCRX area into fixed-size chunks and tracks which are free versus in flight with
+/// the kernel, so callers don't have to compute raw offsets into the area by hand.
+pub(crate) struct AreaAllocator {
+    slots: Vec<Slot>,
+    chunk_size: usize,
+    free_head: u32,
+}
+
+impl AreaAllocator {
+    pub(crate) fn new(area_size: usize, chunk_size: usize) -> io::Result<Self> {
+        if chunk_size == 0 || area_size % chunk_size != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "area_size must be a non-zero multiple of chunk_size",
+            ));
+        }
+
+        let num_slots = area_size / chunk_size;
+        let slots = (0..num_slots)
+            .map(|i| Slot {
+                state: SlotState::Free,
+                next_free: if i + 1 == num_slots {
+                    NONE
+                } else {
+                    (i + 1) as u32
+                },
+            })
+            .collect();
+
+        Ok(Self {
+            slots,
+            chunk_size,
+            free_head: if num_slots == 0 { NONE } else { 0 },
+        })
+    }
+
+    #[inline]
+    pub(crate) fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Pop a free chunk off the free list and mark it in flight, returning its byte offset into
+    /// the area. Returns `None` if there are no free chunks.
+    pub(crate) fn alloc(&mut self) -> Option<u64> {
+        if self.free_head == NONE {
+            return None;
+        }
+
+        let index = self.free_head as usize;
+        self.free_head = self.slots[index].next_free;
+        self.slots[index].state = SlotState::InFlight;
+        Some(index as u64 * self.chunk_size as u64)
+    }
+
+    /// Push the chunk at `offset` back onto the free list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slot at `offset` isn't currently in flight.
+    pub(crate) fn free(&mut self, offset: u64) {
+        let index = (offset / self.chunk_size as u64) as usize;
+        let slot = &mut self.slots[index];
+        assert_eq!(
+            slot.state,
+            SlotState::InFlight,
+            "freed ZCRX area slot {index} that wasn't in flight"
+        );
+        slot.state = SlotState::Free;
+        slot.next_free = self.free_head;
+        self.free_head = index as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_rejects_zero_chunk_size_and_non_multiples() {
+        assert!(AreaAllocator::new(1024, 0).is_err());
+        assert!(AreaAllocator::new(1024, 300).is_err());
+        assert!(AreaAllocator::new(1024, 256).is_ok());
+    }
+
+    #[test]
+    fn alloc_hands_out_every_slot_then_returns_none() {
+        let mut allocator = AreaAllocator::new(1024, 256).unwrap();
+        let mut offsets: Vec<u64> = (0..4).map(|_| allocator.alloc().unwrap()).collect();
+        offsets.sort_unstable();
+        assert_eq!(offsets, vec![0, 256, 512, 768]);
+        assert_eq!(allocator.alloc(), None);
+    }
+
+    #[test]
+    fn free_makes_a_slot_allocatable_again() {
+        let mut allocator = AreaAllocator::new(512, 256).unwrap();
+        let a = allocator.alloc().unwrap();
+        let b = allocator.alloc().unwrap();
+        assert_eq!(allocator.alloc(), None);
+
+        allocator.free(a);
+        assert_eq!(allocator.alloc(), Some(a));
+        assert_eq!(allocator.alloc(), None);
+
+        allocator.free(b);
+        assert_eq!(allocator.alloc(), Some(b));
+    }
+
+    #[test]
+    #[should_panic(expected = "that wasn't in flight")]
+    fn free_panics_on_a_slot_that_isnt_in_flight() {
+        let mut allocator = AreaAllocator::new(256, 256).unwrap();
+        allocator.free(0);
+    }
+
+    #[test]
+    fn chunk_size_reports_the_constructor_argument() {
+        let allocator = AreaAllocator::new(1024, 256).unwrap();
+        assert_eq!(allocator.chunk_size(), 256);
+    }
+}
@@ -1,20 +1,47 @@
 use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
     io,
-    mem::{self, ManuallyDrop}, ops::{Deref, DerefMut}, slice,
+    mem::{self, ManuallyDrop}, ops::{Deref, DerefMut},
+    os::fd::{AsRawFd, BorrowedFd, OwnedFd},
+    slice,
 };
 
 use io_uring::{cqueue, squeue, IoUring};
 use mmap::Mmap;
+pub use mmap::{AreaOptions, HugePageSize};
 
+mod alloc;
 mod mmap;
 pub mod rqueue;
 mod sys;
 
+/// The memory backing a registered ZCRX area.
+enum AreaBacking {
+    /// Anonymous host memory, optionally huge-page-backed (see [`AreaOptions`]).
+    Mmap(ManuallyDrop<Mmap>),
+    /// A pinned dmabuf; its bytes aren't necessarily CPU-mappable.
+    Dmabuf(OwnedFd),
+}
+
+struct AreaEntry {
+    backing: AreaBacking,
+    token: u64,
+    allocator: alloc::AreaAllocator,
+}
+
 pub struct IoUringZcrxIfq {
-    area: ManuallyDrop<Mmap>,
+    /// Registered areas, keyed by the area token the kernel encodes into buffer offsets.
+    areas: HashMap<u64, AreaEntry>,
+    /// Token of the area registered by [`register`](Self::register)/
+    /// [`register_dmabuf`](Self::register_dmabuf), used by [`alloc`](Self::alloc) and
+    /// [`area_token`](Self::area_token).
+    primary_area_token: u64,
+    interface_index: u32,
+    rx_queue_index: u32,
     region: ManuallyDrop<Mmap>,
     rq: rqueue::Inner,
-    area_token: u64,
 }
 
 impl IoUringZcrxIfq {
@@ -24,8 +51,12 @@ impl IoUringZcrxIfq {
         rx_queue_index: u32,
         refill_ring_entries: u32,
         area_size: usize,
+        area_options: AreaOptions,
+        chunk_size: usize,
     ) -> io::Result<Self> {
-        let area = Mmap::new_anon(area_size)?;
+        let area_size = validate_area_size(area_size, area_options.huge_page_size)?;
+        let area = Mmap::new_area(area_size, &area_options)?;
+        let allocator = alloc::AreaAllocator::new(area_size, chunk_size)?;
 
         let page_size = page_size()?;
         let refill_ring_size = page_size
@@ -46,8 +77,20 @@ impl IoUringZcrxIfq {
         }?;
 
         let region_ptr = region.as_mut_ptr();
+        let mut areas = HashMap::new();
+        areas.insert(
+            params.rq_area_token,
+            AreaEntry {
+                backing: AreaBacking::Mmap(ManuallyDrop::new(area)),
+                token: params.rq_area_token,
+                allocator,
+            },
+        );
         Ok(Self {
-            area: ManuallyDrop::new(area),
+            areas,
+            primary_area_token: params.rq_area_token,
+            interface_index,
+            rx_queue_index,
             region: ManuallyDrop::new(region),
             rq: unsafe {
                 rqueue::Inner::new(
@@ -58,19 +101,262 @@ impl IoUringZcrxIfq {
                     params.offset_rqes,
                 )
             },
-            area_token: params.rq_area_token,
         })
     }
 
-    pub unsafe fn get_buf(&self, offset: u64, len: usize) -> Option<BorrowedBuffer> {
-        let data = self
-            .area
-            .as_mut_ptr()
-            .cast::<u8>()
-            .offset(offset as isize);
+    /// Register a ZCRX interface queue whose area is backed by a pinned dmabuf rather than
+    /// anonymous host memory, so packet payloads can land directly in e.g. GPU/accelerator memory.
+    ///
+    /// `dmabuf` is duplicated and the duplicate kept alive for the lifetime of the queue.
+    ///
+    /// Since the area's bytes aren't necessarily CPU-mappable, [`get_buf`](Self::get_buf) and
+    /// [`alloc`](Self::alloc) always return `None` for buffers in this area; use
+    /// [`ZcrxCqe::buffer_offset`]/[`area_token`](Self::area_token) to hand the region off to
+    /// whatever consumes it.
+    pub fn register_dmabuf<S: squeue::EntryMarker>(
+        ring: &IoUring<S, cqueue::Entry32>,
+        interface_index: u32,
+        rx_queue_index: u32,
+        refill_ring_entries: u32,
+        dmabuf: BorrowedFd<'_>,
+        area_size: usize,
+        chunk_size: usize,
+    ) -> io::Result<Self> {
+        let fd = dmabuf.try_clone_to_owned()?;
+        let allocator = alloc::AreaAllocator::new(area_size, chunk_size)?;
+
+        let page_size = page_size()?;
+        let refill_ring_size = page_size
+            + mem::size_of::<rqueue::Entry>() * usize::try_from(refill_ring_entries).unwrap();
+        let page_mask = !(page_size - 1);
+        let region = Mmap::new_anon((refill_ring_size + page_size - 1) & page_mask)?;
+
+        let params = unsafe {
+            ring.submitter().register_zcrx_ifq_dmabuf(
+                interface_index,
+                rx_queue_index,
+                refill_ring_entries,
+                fd.as_raw_fd(),
+                u64::try_from(area_size).unwrap(),
+                region.as_mut_ptr() as u64,
+                u64::try_from(region.len()).unwrap(),
+            )
+        }?;
+
+        let region_ptr = region.as_mut_ptr();
+        let mut areas = HashMap::new();
+        areas.insert(
+            params.rq_area_token,
+            AreaEntry {
+                backing: AreaBacking::Dmabuf(fd),
+                token: params.rq_area_token,
+                allocator,
+            },
+        );
+        Ok(Self {
+            areas,
+            primary_area_token: params.rq_area_token,
+            interface_index,
+            rx_queue_index,
+            region: ManuallyDrop::new(region),
+            rq: unsafe {
+                rqueue::Inner::new(
+                    region_ptr,
+                    params.rq_entries,
+                    params.offset_head,
+                    params.offset_tail,
+                    params.offset_rqes,
+                )
+            },
+        })
+    }
+
+    /// Register an additional host-memory area on this already-registered interface queue,
+    /// returning its area token.
+    ///
+    /// This lets a server dedicate separate areas to different flows or buffer classes while
+    /// sharing one refill queue. Use [`alloc_from`](Self::alloc_from) with the returned token to
+    /// seed the area's chunks into the refill queue.
+    pub fn register_area<S: squeue::EntryMarker>(
+        &mut self,
+        ring: &IoUring<S, cqueue::Entry32>,
+        area_size: usize,
+        area_options: AreaOptions,
+        chunk_size: usize,
+    ) -> io::Result<u64> {
+        let area_size = validate_area_size(area_size, area_options.huge_page_size)?;
+        let area = Mmap::new_area(area_size, &area_options)?;
+        let allocator = alloc::AreaAllocator::new(area_size, chunk_size)?;
+
+        let token = unsafe {
+            ring.submitter().register_zcrx_ifq_area(
+                self.interface_index,
+                self.rx_queue_index,
+                area.as_mut_ptr() as u64,
+                u64::try_from(area.len()).unwrap(),
+            )
+        }?;
+
+        self.areas.insert(
+            token,
+            AreaEntry {
+                backing: AreaBacking::Mmap(ManuallyDrop::new(area)),
+                token,
+                allocator,
+            },
+        );
+
+        Ok(token)
+    }
+
+    /// Register an additional dmabuf-backed area on this already-registered interface queue,
+    /// returning its area token. See [`register_dmabuf`](Self::register_dmabuf).
+    pub fn register_area_dmabuf<S: squeue::EntryMarker>(
+        &mut self,
+        ring: &IoUring<S, cqueue::Entry32>,
+        dmabuf: BorrowedFd<'_>,
+        area_size: usize,
+        chunk_size: usize,
+    ) -> io::Result<u64> {
+        let fd = dmabuf.try_clone_to_owned()?;
+        let allocator = alloc::AreaAllocator::new(area_size, chunk_size)?;
+
+        let token = unsafe {
+            ring.submitter().register_zcrx_ifq_area_dmabuf(
+                self.interface_index,
+                self.rx_queue_index,
+                fd.as_raw_fd(),
+                u64::try_from(area_size).unwrap(),
+            )
+        }?;
+
+        self.areas.insert(
+            token,
+            AreaEntry {
+                backing: AreaBacking::Dmabuf(fd),
+                token,
+                allocator,
+            },
+        );
+
+        Ok(token)
+    }
+
+    /// Allocate a free chunk from the primary area (the one registered by
+    /// [`register`](Self::register)/[`register_dmabuf`](Self::register_dmabuf)), returning a
+    /// buffer to receive into.
+    ///
+    /// Returns `None` if there are no free chunks, or if the primary area is dmabuf-backed and so
+    /// isn't necessarily CPU-mappable.
+    ///
+    /// A chunk handed out by `alloc` stays in circulation between the kernel's refill queue and
+    /// the app (via [`release`](Self::release)/[`recv_buffer`](Self::recv_buffer)) for good — it's
+    /// only returned to the free list by [`retire`](Self::retire).
+    pub fn alloc(&mut self) -> Option<BorrowedBuffer<'_>> {
+        self.alloc_from(self.primary_area_token)
+    }
+
+    /// Allocate a free chunk from the area registered under `area_token` (by
+    /// [`register`](Self::register)/[`register_dmabuf`](Self::register_dmabuf)/
+    /// [`register_area`](Self::register_area)/
+    /// [`register_area_dmabuf`](Self::register_area_dmabuf)), returning a buffer to receive into.
+    ///
+    /// This is the only way to seed a non-primary area's chunks into the refill queue in the
+    /// first place: [`recv_buffer`](Self::recv_buffer)/[`get_buf`](Self::get_buf) can only hand
+    /// back buffers the kernel has already completed a receive into.
+    ///
+    /// Returns `None` if `area_token` isn't registered, has no free chunks, or is dmabuf-backed
+    /// and so isn't necessarily CPU-mappable.
+    pub fn alloc_from(&mut self, area_token: u64) -> Option<BorrowedBuffer<'_>> {
+        let entry = self.areas.get_mut(&area_token)?;
+        if !matches!(entry.backing, AreaBacking::Mmap(_)) {
+            return None;
+        }
+        let offset = entry.allocator.alloc()?;
+        let chunk_size = entry.allocator.chunk_size();
+        let AreaBacking::Mmap(area) = &entry.backing else {
+            unreachable!("checked above");
+        };
+        let data = unsafe { area.as_mut_ptr().cast::<u8>().offset(offset as isize) };
         Some(BorrowedBuffer {
-            slice: slice::from_raw_parts_mut(data, len),
-            off: offset | self.area_token,
+            slice: unsafe { slice::from_raw_parts_mut(data, chunk_size) },
+            off: offset | entry.token,
+        })
+    }
+
+    /// Push `buf` back onto the refill queue so the kernel can DMA into it again.
+    ///
+    /// This does *not* return the chunk to its area's free list: the kernel may still be holding
+    /// onto it as a DMA target, so handing it back out via [`alloc`](Self::alloc) at this point
+    /// would let two owners write to the same memory at once. Use [`retire`](Self::retire) instead
+    /// if you want to permanently pull the chunk out of circulation.
+    ///
+    /// # Safety
+    ///
+    /// See [`RefillQueue::push`](rqueue::RefillQueue::push).
+    pub unsafe fn release(&mut self, buf: BorrowedBuffer<'_>) -> Result<(), rqueue::PushError> {
+        let entry = buf.into_refill_entry();
+        self.refill().push(&entry)
+    }
+
+    /// Permanently remove `buf`'s chunk from circulation, returning it to its area's free list so
+    /// a future [`alloc`](Self::alloc) can dispense it again.
+    ///
+    /// Unlike [`release`](Self::release), this does not push the chunk back to the kernel's
+    /// refill queue, so only call it for a chunk that's truly done being used for zero-copy
+    /// receives (e.g. shrinking the pool, or discarding a buffer instead of recycling it).
+    pub fn retire(&mut self, buf: BorrowedBuffer<'_>) {
+        let offset = buf.buffer_offset();
+        let area_token = buf.area_token();
+        drop(buf);
+        if let Some(area) = self.areas.get_mut(&area_token) {
+            area.allocator.free(offset);
+        }
+    }
+
+    /// Get the buffer a completed zero-copy receive landed in, wrapped in a guard that pushes it
+    /// back to the refill queue automatically when dropped.
+    ///
+    /// # Safety
+    ///
+    /// `cqe` must have come from a zero-copy receive on this interface queue, and the buffer it
+    /// refers to must not already be borrowed. Its area must be host memory, not dmabuf-backed.
+    pub unsafe fn recv_buffer(&mut self, cqe: &ZcrxCqe) -> RecycledBuffer<'_> {
+        let entry = self
+            .areas
+            .get(&cqe.area_token())
+            .expect("recv_buffer: cqe references an unregistered area");
+        let AreaBacking::Mmap(area) = &entry.backing else {
+            panic!("recv_buffer is only valid for host-memory areas");
+        };
+        let offset = cqe.buffer_offset();
+        let data = area.as_mut_ptr().cast::<u8>().offset(offset as isize);
+        let buf = BorrowedBuffer {
+            slice: slice::from_raw_parts_mut(data, cqe.len() as usize),
+            off: offset | entry.token,
+        };
+        RecycledBuffer {
+            ifq: self,
+            buf: Some(buf),
+        }
+    }
+
+    /// Get a CPU-readable slice over the buffer referenced by a completed CQE, dispatching on
+    /// [`ZcrxCqe::area_token`] to find the area it belongs to.
+    ///
+    /// Returns `None` if the area is dmabuf-backed (its bytes aren't necessarily CPU-mappable) or
+    /// isn't registered on this queue; use [`ZcrxCqe::buffer_offset`]/[`ZcrxCqe::area_token`]
+    /// directly in that case.
+    pub unsafe fn get_buf(&self, cqe: &ZcrxCqe) -> Option<BorrowedBuffer> {
+        let entry = self.areas.get(&cqe.area_token())?;
+        let AreaBacking::Mmap(area) = &entry.backing else {
+            return None;
+        };
+        let offset = cqe.buffer_offset();
+        let data = area.as_mut_ptr().cast::<u8>().offset(offset as isize);
+        Some(BorrowedBuffer {
+            slice: slice::from_raw_parts_mut(data, cqe.len() as usize),
+            off: offset | entry.token,
         })
     }
 
@@ -82,7 +368,11 @@ impl IoUringZcrxIfq {
     /// Caller must make sure there is no pending zero-copy receive on the [`IoUring`], or the
     /// [`IoUring`] is dropped.
     pub unsafe fn drop(mut self) {
-        ManuallyDrop::drop(&mut self.area);
+        for entry in self.areas.values_mut() {
+            if let AreaBacking::Mmap(area) = &mut entry.backing {
+                ManuallyDrop::drop(area);
+            }
+        }
         ManuallyDrop::drop(&mut self.region);
     }
 
@@ -103,9 +393,35 @@ impl IoUringZcrxIfq {
         self.rq.borrow_shared()
     }
 
+    /// Get the refill queue, validating that the kernel-reported head/tail satisfy
+    /// `0 <= tail - head <= capacity` first.
+    ///
+    /// Prefer this over [`refill`](Self::refill) when the head/tail come from memory shared with
+    /// something that isn't fully trusted, since [`refill`](Self::refill) trusts them
+    /// unconditionally and a corrupted pair can make [`RefillQueue::len`] wrap to a bogus value.
+    #[inline]
+    pub fn try_refill(&mut self) -> Result<rqueue::RefillQueue<'_>, rqueue::CorruptedQueueState> {
+        self.rq.try_borrow()
+    }
+
+    /// Get the refill queue from a shared reference, validating the kernel-reported head/tail.
+    /// See [`try_refill`](Self::try_refill).
+    ///
+    /// # Safety
+    ///
+    /// No other [`RefillQueue`](rqueue::RefillQueue)s may exist when calling this function.
+    #[inline]
+    pub unsafe fn try_refill_shared(
+        &self,
+    ) -> Result<rqueue::RefillQueue<'_>, rqueue::CorruptedQueueState> {
+        self.rq.try_borrow_shared()
+    }
+
+    /// Token of the primary area, registered by [`register`](Self::register)/
+    /// [`register_dmabuf`](Self::register_dmabuf).
     #[inline]
     pub fn area_token(&self) -> u64 {
-        self.area_token
+        self.primary_area_token
     }
 }
 
@@ -117,14 +433,76 @@ fn page_size() -> io::Result<usize> {
     Ok(ret as usize)
 }
 
+/// Round `size` up to the next multiple of `align`, which must be a power of two.
+fn round_up(size: usize, align: usize) -> io::Result<usize> {
+    debug_assert!(align.is_power_of_two());
+    size.checked_add(align - 1)
+        .map(|sum| sum & !(align - 1))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "area_size is too large to round up to the page size",
+            )
+        })
+}
+
+/// Validate `area_size` against the area's mapping granularity.
+///
+/// When `huge_page_size` is set, the kernel can't pad a host-memory mapping out to a huge page
+/// boundary on our behalf, so `area_size` must already be an exact multiple of it; returns a clear
+/// error instead of silently handing the kernel a larger area than the caller asked for. Without
+/// huge pages, `area_size` is just rounded up to the regular page size as before.
+fn validate_area_size(area_size: usize, huge_page_size: Option<HugePageSize>) -> io::Result<usize> {
+    match huge_page_size {
+        Some(huge_page_size) => {
+            let align = huge_page_size.bytes();
+            if area_size % align != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("area_size must be a multiple of the huge page size ({align} bytes)"),
+                ));
+            }
+            Ok(area_size)
+        }
+        None => round_up(area_size, page_size()?),
+    }
+}
+
 pub struct ZcrxCqe {
     off: u64,
+    len: u32,
+}
+
+/// Error constructing a [`ZcrxCqe`] from a completion that reports a failed receive.
+///
+/// `result()` on the raw completion is negative (an errno) rather than a byte count in this case,
+/// so there's no length to hand back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ZcrxRecvError {
+    /// The errno the kernel reported for the failed receive.
+    pub errno: i32,
 }
 
-impl From<cqueue::Entry32> for ZcrxCqe {
-    fn from(value: cqueue::Entry32) -> Self {
+impl Display for ZcrxRecvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "zero-copy receive completed with errno {}", self.errno)
+    }
+}
+
+impl Error for ZcrxRecvError {}
+
+impl TryFrom<cqueue::Entry32> for ZcrxCqe {
+    type Error = ZcrxRecvError;
+
+    fn try_from(value: cqueue::Entry32) -> Result<Self, Self::Error> {
+        let result = value.result();
+        if result < 0 {
+            return Err(ZcrxRecvError { errno: -result });
+        }
+        let len = result as u32;
         let rcqe: &sys::io_uring_zcrx_cqe = unsafe { mem::transmute(value.big_cqe()) };
-        Self { off: rcqe.off }
+        Ok(Self { off: rcqe.off, len })
     }
 }
 
@@ -132,10 +510,15 @@ impl ZcrxCqe {
     pub fn buffer_offset(&self) -> u64 {
         self.off & !sys::IORING_ZCRX_AREA_MASK
     }
-    
+
     pub fn area_token(&self) -> u64 {
         self.off & sys::IORING_ZCRX_AREA_MASK
     }
+
+    /// Number of bytes received into the buffer.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
 }
 
 pub struct BorrowedBuffer<'a> {
@@ -151,6 +534,14 @@ impl<'a> BorrowedBuffer<'a> {
             __pad: 0,
         })
     }
+
+    pub fn buffer_offset(&self) -> u64 {
+        self.off & !sys::IORING_ZCRX_AREA_MASK
+    }
+
+    pub fn area_token(&self) -> u64 {
+        self.off & sys::IORING_ZCRX_AREA_MASK
+    }
 }
 
 impl<'a> Deref for BorrowedBuffer<'a> {
@@ -166,3 +557,65 @@ impl<'a> DerefMut for BorrowedBuffer<'a> {
         self.slice
     }
 }
+
+/// A [`BorrowedBuffer`] that pushes itself back to the refill queue when dropped, the same way
+/// [`IoUringZcrxIfq::release`] does (and with the same caveat: its chunk stays in circulation
+/// rather than returning to the area's free list — use [`forget`](Self::forget) for that).
+///
+/// Obtained from [`IoUringZcrxIfq::recv_buffer`]. Reading the received data and releasing the
+/// buffer back to the kernel is a single scoped operation, so there's no `into_refill_entry`/
+/// `push` pair to forget.
+pub struct RecycledBuffer<'a> {
+    ifq: &'a mut IoUringZcrxIfq,
+    buf: Option<BorrowedBuffer<'a>>,
+}
+
+impl<'a> RecycledBuffer<'a> {
+    /// Detach the buffer from the guard without pushing it back to the refill queue, handing
+    /// ownership to the caller. Use this to zero-copy hand the buffer off to another subsystem
+    /// that will take care of recycling it itself.
+    pub fn keep(mut self) -> BorrowedBuffer<'a> {
+        self.buf.take().unwrap()
+    }
+
+    /// Detach the buffer from the guard without pushing it back to the refill queue, permanently
+    /// removing its chunk from circulation and returning it to its area's free list. See
+    /// [`IoUringZcrxIfq::retire`].
+    pub fn forget(mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.ifq.retire(buf);
+        }
+    }
+}
+
+impl<'a> Deref for RecycledBuffer<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.buf.as_ref().unwrap()
+    }
+}
+
+impl<'a> DerefMut for RecycledBuffer<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buf.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for RecycledBuffer<'a> {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            let entry = rqueue::Entry(sys::io_uring_zcrx_rqe {
+                off: buf.off,
+                len: buf.slice.len() as u32,
+                __pad: 0,
+            });
+            // If the refill queue is full, fall back to retiring the chunk instead of silently
+            // leaking it: with no other reference to it, a swallowed push error would otherwise
+            // strand it in neither the app's free list nor the kernel's refill queue.
+            if unsafe { self.ifq.refill().push(&entry) }.is_err() {
+                self.ifq.retire(buf);
+            }
+        }
+    }
+}
@@ -1,5 +1,42 @@
 use std::{ffi, io, ptr};
 
+/// Huge page size to request via `MAP_HUGETLB` for an [`AreaOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugePageSize {
+    Mb2,
+    Gb1,
+}
+
+impl HugePageSize {
+    pub(crate) fn bytes(self) -> usize {
+        match self {
+            HugePageSize::Mb2 => 2 * 1024 * 1024,
+            HugePageSize::Gb1 => 1024 * 1024 * 1024,
+        }
+    }
+
+    fn mmap_flag(self) -> libc::c_int {
+        match self {
+            HugePageSize::Mb2 => libc::MAP_HUGE_2MB,
+            HugePageSize::Gb1 => libc::MAP_HUGE_1GB,
+        }
+    }
+}
+
+/// Options controlling how the ZCRX area is mapped.
+///
+/// These let the area be backed by huge pages and/or pinned in memory, which matters since the
+/// NIC DMAs directly into it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AreaOptions {
+    /// Request `MAP_HUGETLB` with the given huge page size.
+    pub huge_page_size: Option<HugePageSize>,
+    /// Request `MAP_LOCKED` so the area can't be swapped out.
+    pub lock: bool,
+    /// Request `MAP_POPULATE` to prefault the area's pages at mmap time.
+    pub populate: bool,
+}
+
 pub(crate) struct Mmap {
     addr: *mut ffi::c_void,
     len: usize,
@@ -23,6 +60,34 @@ impl Mmap {
         Ok(Self { addr, len })
     }
 
+    pub(crate) fn new_area(len: usize, options: &AreaOptions) -> io::Result<Self> {
+        let mut flags = libc::MAP_ANONYMOUS | libc::MAP_PRIVATE;
+        if let Some(huge_page_size) = options.huge_page_size {
+            flags |= libc::MAP_HUGETLB | huge_page_size.mmap_flag();
+        }
+        if options.lock {
+            flags |= libc::MAP_LOCKED;
+        }
+        if options.populate {
+            flags |= libc::MAP_POPULATE;
+        }
+
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { addr, len })
+    }
+
     #[inline]
     pub(crate) fn as_mut_ptr(&self) -> *mut ffi::c_void {
         self.addr
@@ -1,4 +1,4 @@
-use std::{error::Error, ffi, fmt::{self, Display, Formatter, Debug}, sync::atomic::{AtomicU32, Ordering}};
+use std::{error::Error, ffi, fmt::{self, Display, Formatter, Debug}, slice, sync::atomic::{AtomicU32, Ordering}};
 
 use crate::sys;
 
@@ -43,6 +43,29 @@ impl Inner {
     pub(crate) fn borrow(&mut self) -> RefillQueue<'_> {
         unsafe { self.borrow_shared() }
     }
+
+    /// Like [`borrow_shared`](Self::borrow_shared), but checks the invariant
+    /// `0 <= tail - head <= ring_entries` after loading `head` and returns
+    /// [`CorruptedQueueState`] instead of a [`RefillQueue`] whose `len()` would wrap to a bogus
+    /// value if a buggy or hostile peer advanced `head` past `tail`.
+    #[inline]
+    pub(crate) unsafe fn try_borrow_shared(&self) -> Result<RefillQueue<'_>, CorruptedQueueState> {
+        let head = (*self.head).load(Ordering::Acquire);
+        let tail = unsync_load(self.tail);
+        if tail.wrapping_sub(head) > self.ring_entries {
+            return Err(CorruptedQueueState);
+        }
+        Ok(RefillQueue {
+            head,
+            tail,
+            queue: self,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn try_borrow(&mut self) -> Result<RefillQueue<'_>, CorruptedQueueState> {
+        unsafe { self.try_borrow_shared() }
+    }
 }
 
 #[inline(always)]
@@ -114,7 +137,8 @@ impl<'a> RefillQueue<'a> {
     /// cause memory problems.
     #[inline]
     pub unsafe fn push_multiple(&mut self, entries: &[Entry]) -> Result<(), PushError> {
-        if self.capacity() - self.len() < entries.len() {
+        let free = self.capacity().checked_sub(self.len()).ok_or(PushError)?;
+        if free < entries.len() {
             return Err(PushError);
         }
 
@@ -125,6 +149,57 @@ impl<'a> RefillQueue<'a> {
         Ok(())
     }
 
+    /// Get the currently writable region of the ring as up to two contiguous slices, split at the
+    /// point where the free space wraps around the end of the ring.
+    ///
+    /// Fill these directly (e.g. with `copy_from_slice`) and then call
+    /// [`commit`](Self::commit) to publish however many entries were written. This avoids going
+    /// through [`push`](Self::push) one [`Entry`] at a time when refilling large batches of
+    /// buffers.
+    ///
+    /// # Safety
+    ///
+    /// Developers must ensure that the entries written into these slices are valid and will be
+    /// valid for the entire duration of the zero-copy receive operations, otherwise it may cause
+    /// memory problems.
+    #[inline]
+    pub unsafe fn free_regions(&mut self) -> (&mut [Entry], &mut [Entry]) {
+        let free = self.capacity().checked_sub(self.len()).unwrap_or(0);
+        let ring_entries = self.queue.ring_entries as usize;
+        let start = (self.tail & self.queue.ring_mask) as usize;
+
+        if start + free <= ring_entries {
+            (slice::from_raw_parts_mut(self.queue.rqes.add(start), free), &mut [])
+        } else {
+            let first_len = ring_entries - start;
+            (
+                slice::from_raw_parts_mut(self.queue.rqes.add(start), first_len),
+                slice::from_raw_parts_mut(self.queue.rqes, free - first_len),
+            )
+        }
+    }
+
+    /// Advance the tail by `n`, publishing `n` entries previously written via the slices returned
+    /// by [`free_regions`](Self::free_regions).
+    ///
+    /// # Safety
+    ///
+    /// The first `n` entries across the slices returned by the preceding [`free_regions`] call
+    /// must have been filled with valid entries, per its safety contract.
+    ///
+    /// [`free_regions`]: Self::free_regions
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is greater than the queue's current free space (`capacity() - len()`), which
+    /// is the total length of the slices [`free_regions`] returns.
+    #[inline]
+    pub unsafe fn commit(&mut self, n: usize) {
+        let free = self.capacity().checked_sub(self.len()).unwrap_or(0);
+        assert!(n <= free, "commit({n}) would overflow the refill queue");
+        self.tail = self.tail.wrapping_add(n as u32);
+    }
+
     #[inline]
     unsafe fn push_unchecked(&mut self, entry: &Entry) {
         *self
@@ -178,6 +253,22 @@ impl Display for PushError {
 
 impl Error for PushError {}
 
+/// Error returned by [`IoUringZcrxIfq::try_refill`](crate::IoUringZcrxIfq::try_refill) when the
+/// kernel-reported `head`/`tail` violate the ring buffer invariant
+/// `0 <= tail - head <= ring_entries`, which a buggy or hostile peer could otherwise use to make
+/// `len()` wrap to a bogus value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CorruptedQueueState;
+
+impl Display for CorruptedQueueState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("refill queue head/tail violate the ring buffer invariant")
+    }
+}
+
+impl Error for CorruptedQueueState {}
+
 impl Debug for RefillQueue<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let mut d = f.debug_list();
@@ -190,3 +281,134 @@ impl Debug for RefillQueue<'_> {
         d.finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAP: usize = 8;
+
+    /// A heap-backed stand-in for the mmap'd region an `Inner` normally points into, so the ring
+    /// math can be exercised without a real io_uring registration.
+    #[repr(C, align(8))]
+    struct RawRegion {
+        head: u32,
+        _pad0: u32,
+        tail: u32,
+        _pad1: u32,
+        rqes: [Entry; CAP],
+    }
+
+    struct TestRing {
+        // Kept alive for as long as `inner`'s raw pointers point into it; never read directly.
+        #[allow(dead_code)]
+        region: Box<RawRegion>,
+        inner: Inner,
+    }
+
+    impl TestRing {
+        fn new(ring_entries: u32) -> Self {
+            assert!(ring_entries as usize <= CAP);
+            let zero = Entry(sys::io_uring_zcrx_rqe {
+                off: 0,
+                len: 0,
+                __pad: 0,
+            });
+            let mut region = Box::new(RawRegion {
+                head: 0,
+                _pad0: 0,
+                tail: 0,
+                _pad1: 0,
+                rqes: [zero; CAP],
+            });
+            let inner = unsafe {
+                Inner::new(
+                    (&mut *region as *mut RawRegion).cast(),
+                    ring_entries,
+                    0,
+                    8,
+                    16,
+                )
+            };
+            Self { region, inner }
+        }
+
+        /// Simulate the kernel consuming `n` entries from the head side.
+        fn advance_head(&self, n: u32) {
+            let head = unsafe { &*self.inner.head };
+            let new_head = head.load(Ordering::Acquire).wrapping_add(n);
+            head.store(new_head, Ordering::Release);
+        }
+    }
+
+    fn entry(off: u64) -> Entry {
+        Entry(sys::io_uring_zcrx_rqe {
+            off,
+            len: 0,
+            __pad: 0,
+        })
+    }
+
+    #[test]
+    fn push_increases_len_and_respects_capacity() {
+        let mut ring = TestRing::new(2);
+        let mut rq = ring.inner.borrow();
+        assert_eq!(rq.capacity(), 2);
+        assert!(rq.is_empty());
+
+        unsafe {
+            rq.push(&entry(1)).unwrap();
+            assert_eq!(rq.len(), 1);
+            rq.push(&entry(2)).unwrap();
+            assert!(rq.is_full());
+            assert_eq!(rq.push(&entry(3)), Err(PushError));
+        }
+    }
+
+    #[test]
+    fn free_regions_splits_at_the_wraparound_point() {
+        let mut ring = TestRing::new(4);
+        let mut rq = ring.inner.borrow();
+        unsafe {
+            rq.push_multiple(&[entry(1), entry(2), entry(3)]).unwrap();
+        }
+        drop(rq); // publishes tail == 3
+        ring.advance_head(3); // kernel consumes all 3, head == tail == 3
+
+        let mut rq = ring.inner.borrow();
+        let (first, second) = unsafe { rq.free_regions() };
+        assert_eq!(first.len(), 1); // slot 3 is the only one before wrapping back to index 0
+        assert_eq!(second.len(), 3);
+    }
+
+    #[test]
+    fn commit_publishes_entries_written_via_free_regions() {
+        let mut ring = TestRing::new(4);
+        let mut rq = ring.inner.borrow();
+        unsafe {
+            let (first, _second) = rq.free_regions();
+            first[0] = entry(42);
+            rq.commit(1);
+        }
+        assert_eq!(rq.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "commit(3) would overflow the refill queue")]
+    fn commit_panics_past_the_free_space() {
+        let mut ring = TestRing::new(2);
+        let mut rq = ring.inner.borrow();
+        unsafe { rq.commit(3) };
+    }
+
+    #[test]
+    fn try_borrow_rejects_a_head_ahead_of_tail() {
+        let mut ring = TestRing::new(4);
+        // head ahead of tail makes tail.wrapping_sub(head) wrap to a value far above ring_entries.
+        ring.advance_head(1);
+        assert!(matches!(
+            ring.inner.try_borrow(),
+            Err(CorruptedQueueState)
+        ));
+    }
+}